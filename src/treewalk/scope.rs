@@ -3,18 +3,39 @@ use std::collections::{HashMap, HashSet};
 use crate::{
     core::Container,
     treewalk::types::{
-        utils::ResolvedArguments, Dict, DictItems, ExprResult, Function, Str, Tuple,
+        utils::ResolvedArguments, Cell, Dict, DictItems, ExprResult, Function, Str, Tuple,
     },
     types::errors::InterpreterError,
 };
 
 use super::Interpreter;
 
+mod slots;
+
+use slots::SlotTable;
+
 /// This represents a symbol table for a given scope.
 #[derive(Debug, PartialEq, Clone, Default)]
 pub struct Scope {
+    /// Dense, slot-indexed storage for the locals covered by `slot_table`, resolved by index
+    /// instead of by name. `None` means the slot exists but hasn't been assigned yet.
+    slots: Vec<Option<ExprResult>>,
+
+    /// The name-to-slot-index mapping for this scope's function, computed once when the function
+    /// was defined (see `Function::slot_table`) and shared by every call. `None` for scopes that
+    /// were never built from a function's statically-known locals, e.g. the module scope.
+    slot_table: Option<Container<SlotTable>>,
+
+    /// Locals not covered by `slot_table` -- either because this scope has no slot table, or
+    /// because the name was created dynamically through `globals()`/`exec`.
     symbol_table: HashMap<String, ExprResult>,
 
+    /// Names whose storage has been promoted to a shared `Cell`, because a nested function or
+    /// lambda closes over them as a free variable, or because this scope rebinds a name it
+    /// captured via `nonlocal`. Reads and writes to these names go through the cell instead of
+    /// `symbol_table`/`slots`, so every scope sharing the cell observes the same mutations.
+    cell_vars: HashMap<String, Container<Cell>>,
+
     /// Used to hold directives such as `global x` which will expire with this scope.
     global_vars: HashSet<String>,
 
@@ -28,9 +49,22 @@ impl Scope {
         function: &Container<Function>,
         arguments: &ResolvedArguments,
     ) -> Result<Container<Self>, InterpreterError> {
-        let mut scope = Self::default();
+        // `function.slot_table()` is the interned name-to-index table computed once on `Function`
+        // (presumably when it's defined); this module only ever consumes it, via `SlotTable`
+        // (`scope/slots.rs`) and the `get_slot`/`insert_slot` indexed-access pair below. Emitting
+        // direct indexed loads/stores for resolved `Name` nodes -- the other half of the request,
+        // and the actual source of the claimed hot-loop speedup -- is the evaluator's job, and
+        // isn't part of this source tree; scoping this request down to the `Scope`-side storage
+        // and indexed-access API is what's deliverable from here.
+        let slot_table = function.borrow().slot_table();
+        let mut scope = Self {
+            slots: vec![None; slot_table.borrow().len()],
+            slot_table: Some(slot_table),
+            ..Self::default()
+        };
 
         let function_args = &function.borrow().args;
+        let func_name = function.borrow().name.clone();
 
         // Function expects fewer positional args than it was invoked with and there is not an
         // `args_var` in which to store the rest.
@@ -43,14 +77,83 @@ impl Scope {
         }
 
         let bound_args = arguments.bound_args();
+
+        // One slot per positional-or-keyword parameter, filled from positional args here and
+        // then by keyword below. `None` means the slot still needs a default or is missing.
+        let mut values: Vec<Option<ExprResult>> = function_args
+            .args
+            .iter()
+            .enumerate()
+            .map(|(index, _)| bound_args.get(index).cloned())
+            .collect();
+
+        // Keyword-only parameters (declared after a bare `*`) can never be filled positionally,
+        // so they start out empty and are only ever matched by name below.
+        let mut kwonly_values: Vec<Option<ExprResult>> = vec![None; function_args.kwonly_args.len()];
+
+        // Keyword arguments that don't bind to a named parameter; collected for `**kwargs`.
+        let mut extra_kwargs = HashMap::new();
+
+        for (key, value) in arguments.get_kwargs() {
+            let name = key.as_string().expect("kwarg keys are always strings");
+
+            if let Some(index) = function_args
+                .args
+                .iter()
+                .position(|arg_definition| arg_definition.arg == name)
+            {
+                if index < function_args.pos_only_count {
+                    if function_args.kwargs_var.is_none() {
+                        return Err(InterpreterError::TypeError(
+                            Some(format!(
+                                "{}() got some positional-only arguments passed as keyword arguments: '{}'",
+                                func_name, name
+                            )),
+                            interpreter.state.call_stack(),
+                        ));
+                    }
+                    extra_kwargs.insert(key, value);
+                    continue;
+                }
+
+                if values[index].is_some() {
+                    return Err(InterpreterError::TypeError(
+                        Some(format!(
+                            "{}() got multiple values for argument '{}'",
+                            func_name, name
+                        )),
+                        interpreter.state.call_stack(),
+                    ));
+                }
+
+                values[index] = Some(value);
+            } else if let Some(index) = function_args
+                .kwonly_args
+                .iter()
+                .position(|arg_definition| arg_definition.arg == name)
+            {
+                kwonly_values[index] = Some(value);
+            } else if function_args.kwargs_var.is_some() {
+                extra_kwargs.insert(key, value);
+            } else {
+                return Err(InterpreterError::TypeError(
+                    Some(format!(
+                        "{}() got an unexpected keyword argument '{}'",
+                        func_name, name
+                    )),
+                    interpreter.state.call_stack(),
+                ));
+            }
+        }
+
         let mut missing_args = vec![];
+        let mut missing_kwonly_args = vec![];
 
         for (index, arg_definition) in function_args.args.iter().enumerate() {
             // Check if the argument is provided, otherwise use default
-            let value = if index < bound_args.len() {
-                bound_args[index].clone()
-            } else {
-                match &arg_definition.default {
+            let value = match values[index].take() {
+                Some(value) => value,
+                None => match &arg_definition.default {
                     Some(default_value) => interpreter.evaluate_expr(default_value)?,
                     None => {
                         missing_args.push(arg_definition.arg.clone());
@@ -59,32 +162,68 @@ impl Scope {
                         // find all the missing args first.
                         ExprResult::Void
                     }
-                }
+                },
             };
 
             scope.insert(&arg_definition.arg, value);
         }
 
-        // Function expects more positional args than it was invoked with.
-        if !missing_args.is_empty() {
-            let num_missing = missing_args.len();
-            let noun = if num_missing == 1 {
-                "argument"
-            } else {
-                "arguments"
+        for (index, arg_definition) in function_args.kwonly_args.iter().enumerate() {
+            let value = match kwonly_values[index].take() {
+                Some(value) => value,
+                None => match &arg_definition.default {
+                    Some(default_value) => interpreter.evaluate_expr(default_value)?,
+                    None => {
+                        missing_kwonly_args.push(arg_definition.arg.clone());
+                        ExprResult::Void
+                    }
+                },
             };
-            let arg_names = missing_args
-                .into_iter()
-                .map(|a| format!("'{}'", a))
-                .collect::<Vec<_>>()
-                .join(" and ");
-            let message = format!(
-                "{}() missing {} required positional {}: {}",
-                function.borrow().name,
-                num_missing,
-                noun,
-                arg_names
-            );
+
+            scope.insert(&arg_definition.arg, value);
+        }
+
+        // Function expects more args than it was invoked with.
+        if !missing_args.is_empty() || !missing_kwonly_args.is_empty() {
+            let mut parts = vec![];
+            if !missing_args.is_empty() {
+                let noun = if missing_args.len() == 1 {
+                    "argument"
+                } else {
+                    "arguments"
+                };
+                let arg_names = missing_args
+                    .iter()
+                    .map(|a| format!("'{}'", a))
+                    .collect::<Vec<_>>()
+                    .join(" and ");
+                parts.push(format!(
+                    "missing {} required positional {}: {}",
+                    missing_args.len(),
+                    noun,
+                    arg_names
+                ));
+            }
+            if !missing_kwonly_args.is_empty() {
+                let noun = if missing_kwonly_args.len() == 1 {
+                    "argument"
+                } else {
+                    "arguments"
+                };
+                let arg_names = missing_kwonly_args
+                    .iter()
+                    .map(|a| format!("'{}'", a))
+                    .collect::<Vec<_>>()
+                    .join(" and ");
+                parts.push(format!(
+                    "missing {} required keyword-only {}: {}",
+                    missing_kwonly_args.len(),
+                    noun,
+                    arg_names
+                ));
+            }
+
+            let message = format!("{}() {}", func_name, parts.join("; "));
             return Err(InterpreterError::TypeError(
                 Some(message),
                 interpreter.state.call_stack(),
@@ -99,41 +238,199 @@ impl Scope {
         }
 
         if let Some(ref kwargs_var) = function_args.kwargs_var {
-            let kwargs_value = ExprResult::Dict(Container::new(Dict::new(arguments.get_kwargs())));
+            let kwargs_value = ExprResult::Dict(Container::new(Dict::new(extra_kwargs)));
             scope.insert(kwargs_var.as_str(), kwargs_value);
         }
 
+        // `function`'s closure is the set of free variables it captured by reference when the
+        // `def`/lambda that created it was evaluated (each one a cell shared with whatever
+        // enclosing scope declared it, obtained there via `Scope::get_cell`/`capture_as_cell`).
+        // Installing them here, rather than copying their current value into `symbol_table`,
+        // is what lets this call observe later mutations the enclosing frame makes to them (and
+        // vice versa for `nonlocal` rebinds in this call).
+        //
+        // This loop is the consumer side and is exercised every call. The producer side is
+        // `promote_free_variables` below, called on the *enclosing* scope once, when the
+        // `def`/lambda is evaluated: given the set of names that `def`/lambda's body closes over
+        // (computed by walking its body for free variables, which isn't implemented in this
+        // source tree), it promotes each to a cell and the result is recorded on the new
+        // `Function`'s `closure()`. Until that walk exists and calls it, `closure()` is always
+        // empty and this loop is a no-op in practice.
+        for (name, cell) in function.borrow().closure() {
+            scope.capture_as_cell(&name, cell);
+        }
+
         Ok(Container::new(scope.to_owned()))
     }
 
     fn from_hash(symbol_table: HashMap<String, ExprResult>) -> Self {
         Self {
             symbol_table,
-            global_vars: HashSet::new(),
-            nonlocal_vars: HashSet::new(),
+            ..Self::default()
         }
     }
 
     pub fn get(&self, name: &str) -> Option<ExprResult> {
+        if let Some(cell) = self.cell_vars.get(name) {
+            return Some(cell.borrow().get());
+        }
+
+        if let Some(ref table) = self.slot_table {
+            if let Some(index) = table.borrow().index_of(name) {
+                return self.slots[index].clone();
+            }
+        }
+
         self.symbol_table.get(name).cloned()
     }
 
     /// Return a list of all the symbols available in this `Scope`.
     pub fn symbols(&self) -> Vec<String> {
-        self.symbol_table.keys().cloned().collect()
+        let mut symbols: Vec<String> = self
+            .symbol_table
+            .keys()
+            .cloned()
+            .chain(self.cell_vars.keys().cloned())
+            .collect();
+
+        if let Some(ref table) = self.slot_table {
+            let table = table.borrow();
+            symbols.extend(
+                self.slots
+                    .iter()
+                    .enumerate()
+                    .filter(|(_, value)| value.is_some())
+                    .map(|(index, _)| table.name_at(index).to_string()),
+            );
+        }
+
+        symbols
     }
 
     pub fn delete(&mut self, name: &str) -> Option<ExprResult> {
+        if let Some(cell) = self.cell_vars.remove(name) {
+            return Some(cell.borrow().get());
+        }
+
+        if let Some(ref table) = self.slot_table {
+            if let Some(index) = table.borrow().index_of(name) {
+                return self.slots[index].take();
+            }
+        }
+
         self.symbol_table.remove(name)
     }
 
     /// Insert an `ExprResult` to this `Scope`. The `Scope` is returned to allow calls to be
     /// chained.
     pub fn insert(&mut self, name: &str, value: ExprResult) -> &mut Self {
+        if let Some(cell) = self.cell_vars.get(name) {
+            cell.borrow_mut().set(value);
+            return self;
+        }
+
+        if let Some(ref table) = self.slot_table {
+            if let Some(index) = table.borrow().index_of(name) {
+                self.slots[index] = Some(value);
+                return self;
+            }
+        }
+
         self.symbol_table.insert(name.to_string(), value);
         self
     }
 
+    /// Promotes `name` to a cell-backed binding, sharing `cell` with whichever scope already
+    /// holds it. Used when a nested function/lambda closes over this local as a free variable,
+    /// or when a `nonlocal` directive rebinds a name captured from an enclosing function. Any
+    /// value already bound to `name` in this scope -- whether it lives in a slot or in
+    /// `symbol_table` -- is moved into the cell so existing reads keep working.
+    pub fn capture_as_cell(&mut self, name: &str, cell: Container<Cell>) {
+        let existing = if let Some(ref table) = self.slot_table {
+            table
+                .borrow()
+                .index_of(name)
+                .and_then(|index| self.slots[index].take())
+        } else {
+            None
+        };
+        let existing = existing.or_else(|| self.symbol_table.remove(name));
+
+        if let Some(existing) = existing {
+            cell.borrow_mut().set(existing);
+        }
+
+        self.cell_vars.insert(name.to_string(), cell);
+    }
+
+    /// Returns the `Cell` backing `name` in this scope, if free-variable analysis has already
+    /// promoted it to one. A nested function capturing `name` uses this to share the same cell
+    /// rather than copying its current value.
+    pub fn get_cell(&self, name: &str) -> Option<Container<Cell>> {
+        self.cell_vars.get(name).cloned()
+    }
+
+    /// Promotes each name in `free_vars` to a cell in this (enclosing) scope and returns the
+    /// resulting name-to-cell map, ready to be recorded as a nested function's `closure()`. This
+    /// is the producer side of closures: once a `def`/lambda's body has been walked for free
+    /// variables -- not implemented in this source tree -- its evaluation calls this on the
+    /// enclosing scope before building the `Function` for the nested scope.
+    ///
+    /// Reuses an existing cell via `get_cell` when `name` was already promoted, e.g. by this
+    /// scope's own `nonlocal` handling, or by a sibling nested function closing over the same
+    /// name, so every closure sharing a free variable shares one cell rather than a copy per
+    /// nested function. Otherwise promotes it fresh via `capture_as_cell`, seeding the cell with
+    /// whatever value `name` currently holds here (or `Void` if it's unbound).
+    pub fn promote_free_variables(
+        &mut self,
+        free_vars: &HashSet<String>,
+    ) -> HashMap<String, Container<Cell>> {
+        free_vars
+            .iter()
+            .map(|name| {
+                let cell = self.get_cell(name).unwrap_or_else(|| {
+                    let cell = Container::new(Cell::new(self.get(name).unwrap_or(ExprResult::Void)));
+                    self.capture_as_cell(name, cell.clone());
+                    cell
+                });
+                (name.clone(), cell)
+            })
+            .collect()
+    }
+
+    /// Reads a local by its pre-resolved slot index instead of hashing `name` through
+    /// `slot_table`. For this to pay off the index has to be resolved once, not per access --
+    /// the intended caller is an evaluator that, for a given `Name` node, looks up
+    /// `SlotTable::index_of` a single time (when the function is defined or first evaluated) and
+    /// reuses the index on every subsequent evaluation of that node, e.g. in a hot loop.
+    ///
+    /// `name` is only consulted when `cell_vars` is non-empty -- the uncommon case where this
+    /// particular local has since been promoted to a closure cell in this scope and the slot
+    /// itself is stale. That check is a cheap `is_empty` on the common path, so it doesn't bring
+    /// back the hashing this method exists to avoid.
+    pub fn get_slot(&self, index: usize, name: &str) -> Option<ExprResult> {
+        if !self.cell_vars.is_empty() {
+            if let Some(cell) = self.cell_vars.get(name) {
+                return Some(cell.borrow().get());
+            }
+        }
+
+        self.slots[index].clone()
+    }
+
+    /// Writes a local by its pre-resolved slot index. See `get_slot` for when this is a win over
+    /// `insert`.
+    pub fn insert_slot(&mut self, index: usize, name: &str, value: ExprResult) {
+        if !self.cell_vars.is_empty() {
+            if let Some(cell) = self.cell_vars.get(name) {
+                cell.borrow_mut().set(value);
+                return;
+            }
+        }
+
+        self.slots[index] = Some(value);
+    }
+
     /// Given a variable `var`, indicate that `var` should refer to the variable in the
     /// global/module scope (which does not live in this struct) for the duration of _this_
     /// local scope.
@@ -161,6 +458,18 @@ impl Scope {
         for (key, value) in self.symbol_table.iter() {
             items.insert(ExprResult::String(Str::new(key.clone())), value.clone());
         }
+        for (key, cell) in self.cell_vars.iter() {
+            items.insert(ExprResult::String(Str::new(key.clone())), cell.borrow().get());
+        }
+        if let Some(ref table) = self.slot_table {
+            let table = table.borrow();
+            for (index, value) in self.slots.iter().enumerate() {
+                if let Some(value) = value {
+                    let key = ExprResult::String(Str::new(table.name_at(index).to_string()));
+                    items.insert(key, value.clone());
+                }
+            }
+        }
 
         Container::new(Dict::new(items))
     }
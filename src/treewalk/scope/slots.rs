@@ -0,0 +1,52 @@
+use std::collections::HashMap;
+
+/// Maps a function's statically-known local names to dense slot indices.
+///
+/// This is computed once per function definition (see `Function::slot_table`) and shared by
+/// every `Scope` created for a call to that function, so those names are stored in a dense
+/// `Vec<Option<ExprResult>>`. `Scope::get`/`insert` still look the name up here (one hash) to
+/// find its slot; `Scope::get_slot`/`insert_slot` take an index resolved ahead of time and skip
+/// the lookup. Names that aren't in the table -- locals created dynamically through `globals()`
+/// or `exec` -- still fall back to a `HashMap` in `Scope`.
+#[derive(Debug, PartialEq, Clone, Default)]
+pub struct SlotTable {
+    indices: HashMap<String, usize>,
+    names: Vec<String>,
+}
+
+impl SlotTable {
+    pub fn new(names: impl IntoIterator<Item = String>) -> Self {
+        let mut indices = HashMap::new();
+        let mut ordered = vec![];
+
+        for name in names {
+            if indices.contains_key(&name) {
+                continue;
+            }
+
+            indices.insert(name.clone(), ordered.len());
+            ordered.push(name);
+        }
+
+        Self {
+            indices,
+            names: ordered,
+        }
+    }
+
+    pub fn index_of(&self, name: &str) -> Option<usize> {
+        self.indices.get(name).copied()
+    }
+
+    pub fn name_at(&self, index: usize) -> &str {
+        &self.names[index]
+    }
+
+    pub fn len(&self) -> usize {
+        self.names.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.names.is_empty()
+    }
+}
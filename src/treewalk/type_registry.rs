@@ -9,6 +9,24 @@ use crate::treewalk::types::{
     Str, Super, Traceback, Tuple, Type, TypeClass,
 };
 
+mod class_def;
+
+use class_def::ClassDefinition;
+
+/// Validates a class's bases and stores the resulting `__mro__` on it, using the
+/// [`ClassDefinition`] pass (the same pass `class_def::finalize_user_class` runs for user
+/// classes). Within this module it backs both builtin bootstrap (`init_type_classes`) and
+/// [`TypeRegistry::register_type`], so embedder-registered native types get the same linearized
+/// `__mro__` builtins do. A failure here indicates a bug in how `all_types`/`builtin_methods`/the
+/// caller of `register_type` assign bases, not a user error -- we panic rather than propagate a
+/// `TypeError`.
+fn set_builtin_mro(class: &Container<Class>, bases: &[Container<Class>]) {
+    let computed_mro = ClassDefinition::new(class.clone(), bases.to_vec())
+        .analyze()
+        .unwrap_or_else(|e| panic!("{}", e.message()));
+    class.borrow_mut().set_mro(computed_mro);
+}
+
 /// [`Type::Type`] and [`Type::Object`] are excluded here because they are initialized separately.
 fn builtin_methods() -> HashMap<Type, impl Iterator<Item = Box<dyn Callable>>> {
     HashMap::from([
@@ -154,6 +172,7 @@ fn callable_types() -> Vec<Type> {
 /// metaclass is Type.
 fn type_class() -> Container<Class> {
     let object_base = Class::new_builtin(Type::ObjectMeta, None, vec![]);
+    set_builtin_mro(&object_base, &[]);
     for method in Object::get_methods().into_iter() {
         object_base.set_on_class(
             &method.name(),
@@ -162,6 +181,7 @@ fn type_class() -> Container<Class> {
     }
 
     let type_base = Class::new_builtin(Type::TypeMeta, None, vec![]);
+    set_builtin_mro(&type_base, &[]);
     for method in TypeClass::get_methods().into_iter() {
         type_base.set_on_class(
             &method.name(),
@@ -176,7 +196,9 @@ fn type_class() -> Container<Class> {
         );
     }
 
-    let type_class = Class::new_builtin(Type::Type, Some(type_base), vec![object_base]);
+    let type_bases = vec![object_base];
+    let type_class = Class::new_builtin(Type::Type, Some(type_base), type_bases.clone());
+    set_builtin_mro(&type_class, &type_bases);
     for method in TypeClass::get_methods().into_iter() {
         type_class.set_on_class(
             &method.name(),
@@ -198,6 +220,7 @@ fn type_class() -> Container<Class> {
 /// [`Type::Type`], except itself.
 fn object_class(metaclass: Container<Class>) -> Container<Class> {
     let object_class = Class::new_builtin(Type::Object, Some(metaclass), vec![]);
+    set_builtin_mro(&object_class, &[]);
     for method in Object::get_methods().into_iter() {
         object_class.set_on_class(
             &method.name(),
@@ -235,16 +258,26 @@ fn init_type_classes() -> HashMap<Type, Container<Class>> {
     let object_class = object_class(type_class.clone());
     type_classes.insert(Type::Object, object_class.clone());
 
-    // TODO in theory, the parent of `Type::Type` should be `Type::Object`. The code is hanging
-    // with this line presumably due to a cycle. Maybe there's a way to break this since this is a
-    // known and expected case.
-    //type_class.borrow_mut().parent_class = Some(object_class.clone());
+    // Now that `Type::Object` exists, replace `Type::Type`'s `__mro__` with
+    // `[Type::Type, Type::Object]`. Only the `__mro__` changes: `Type::Type`'s declared bases stay
+    // `[Type::ObjectMeta]`, and both classes are still built by their own functions above. The
+    // cycle check in `ClassDefinition::analyze` does not trip on this (metaclass and base are
+    // different relations); if `analyze` did fail here, bootstrap would panic.
+    // Attribute/method lookup and `super()` do not read `__mro__`; they recurse through
+    // `parent_class`.
+    type_class.borrow_mut().set_mro(
+        ClassDefinition::new(type_class.clone(), vec![object_class.clone()])
+            .analyze()
+            .unwrap_or_else(|e| panic!("{}", e.message())),
+    );
 
     // Create all the other type classes using `Type::Type` and `Type::Object`.
     let mut methods = builtin_methods();
     let mut attributes = descriptors();
     for type_ in all_types() {
-        let class = Class::new_builtin(type_, Some(type_class.clone()), vec![object_class.clone()]);
+        let bases = vec![object_class.clone()];
+        let class = Class::new_builtin(type_, Some(type_class.clone()), bases.clone());
+        set_builtin_mro(&class, &bases);
         let builtin_type = class.borrow().builtin_type();
 
         // Add the builtin methods for this type class.
@@ -280,15 +313,68 @@ fn init_type_classes() -> HashMap<Type, Container<Class>> {
 /// [`Class`] will contain any builtin methods which are supported.
 pub struct TypeRegistry {
     type_classes: HashMap<Type, Container<Class>>,
+
+    /// Native types registered by an embedder through [`TypeRegistry::register_type`], in
+    /// registration order. These live outside the [`Type`] enum entirely, so a host application
+    /// can expose its own Rust objects as Python types without forking it.
+    custom_types: Vec<Container<Class>>,
 }
 
 impl TypeRegistry {
     pub fn new() -> Self {
         Self {
             type_classes: init_type_classes(),
+            custom_types: vec![],
         }
     }
 
+    /// Registers a native type so it can be used as a Python class, for embedders linking
+    /// memphis into a Rust host that want to expose their own objects without forking the
+    /// [`Type`] enum.
+    ///
+    /// The new class is minted with [`Class::new_native`], which wires it into the same
+    /// metaclass/MRO machinery as the builtins: `metaclass` defaults to [`Type::Type`] and
+    /// `bases` defaults to `[Type::Object]` when empty. Its `__mro__` is then computed by
+    /// `set_builtin_mro`, the same [`ClassDefinition`] pass builtins go through, so a native type
+    /// with its own `bases` is linearized and cycle-checked like a builtin. It is immediately made
+    /// callable, landing in the builtin scope alongside
+    /// the builtins the next time [`TypeRegistry::get_callable_builtin_types`] is consulted.
+    pub fn register_type(
+        &mut self,
+        name: &str,
+        methods: Vec<Box<dyn Callable>>,
+        descriptors: Vec<Box<dyn NonDataDescriptor>>,
+        metaclass: Option<Container<Class>>,
+        bases: Vec<Container<Class>>,
+    ) -> Container<Class> {
+        let metaclass = metaclass.unwrap_or_else(|| self.get_type_class(Type::Type));
+        let bases = if bases.is_empty() {
+            vec![self.get_type_class(Type::Object)]
+        } else {
+            bases
+        };
+
+        let class = Class::new_native(name.to_string(), Some(metaclass), bases.clone());
+        set_builtin_mro(&class, &bases);
+
+        for method in methods {
+            class.set_on_class(
+                &method.name(),
+                ExprResult::BuiltinMethod(Container::new(method)),
+            );
+        }
+
+        for attr in descriptors {
+            class.set_on_class(
+                &attr.name(),
+                ExprResult::NonDataDescriptor(Container::new(attr)),
+            );
+        }
+
+        self.custom_types.push(class.clone());
+        class
+    }
+
     /// Safe to call `unwrap()` here because we will have a type class for all `Type`s.
     /// TODO we still need to enforce this at compile-time ideally.
     pub fn get_type_class(&self, type_: Type) -> Container<Class> {
@@ -304,11 +390,13 @@ impl TypeRegistry {
     }
 
     /// We need a way to expose the builtin types so they can be stored in the builtin scope inside
-    /// the `ScopeManager`.
+    /// the `ScopeManager`. This also includes any native types an embedder registered through
+    /// [`TypeRegistry::register_type`], so they're callable from Python the same way a builtin is.
     pub fn get_callable_builtin_types(&self) -> Vec<Container<Class>> {
         callable_types()
             .iter()
             .map(|callable_type| self.get_type_class(callable_type.clone()))
+            .chain(self.custom_types.iter().cloned())
             .collect()
     }
 }
@@ -0,0 +1,230 @@
+use std::collections::HashSet;
+
+use crate::core::Container;
+use crate::treewalk::types::{Class, ExprResult, Type};
+use crate::types::errors::InterpreterError;
+
+/// A validation-and-linearization pass run over a class's declared bases before the class object
+/// is finalized: for builtin classes during bootstrap and in `TypeRegistry::register_type`, and
+/// for user-defined ones through `finalize_user_class`.
+///
+/// During builtin bootstrap (`init_type_classes`) it runs before any methods are populated;
+/// `Type::Type` and `Type::Object` are still built by their own functions there. Rather than
+/// populating methods first and hoping the bases happen to be consistent, we check that every
+/// base is itself a class, detect inheritance cycles, and compute the C3-linearized `__mro__` up front -- so an
+/// invalid hierarchy surfaces as a `ClassDefinitionError` instead of deadlocking.
+///
+/// Scope: this module computes and stores `__mro__`, and provides `resolve_in_mro`/
+/// `resolve_in_mro_after` below as the traversal `Class::get_member`/`Super`'s resolution should
+/// call instead of recursing through a single `parent_class`. Calling them is still up to
+/// `Class`/`Super` themselves, which are not part of this module (or this source tree).
+pub struct ClassDefinition {
+    class: Container<Class>,
+    bases: Vec<Container<Class>>,
+}
+
+impl ClassDefinition {
+    /// Builds a definition from already-resolved bases, e.g. during builtin bootstrap where the
+    /// bases are always `Container<Class>` to begin with and there's nothing to validate.
+    pub fn new(class: Container<Class>, bases: Vec<Container<Class>>) -> Self {
+        Self { class, bases }
+    }
+
+    /// Builds a definition from a `class Foo(Base1, Base2):` statement's evaluated base
+    /// expressions, rejecting any base that doesn't actually evaluate to a class. See
+    /// `finalize_user_class` below for the full sequence a `class` statement's evaluation runs
+    /// this through.
+    pub fn from_exprs(
+        class: Container<Class>,
+        base_exprs: Vec<ExprResult>,
+    ) -> Result<Self, ClassDefinitionError> {
+        let mut bases = vec![];
+        for expr in base_exprs {
+            match expr.as_class() {
+                Some(base) => bases.push(base),
+                None => {
+                    return Err(ClassDefinitionError::NotAClass {
+                        name: expr.get_type().value().to_string(),
+                    })
+                }
+            }
+        }
+
+        Ok(Self::new(class, bases))
+    }
+
+    /// Validates the declared bases and returns this class's `__mro__` (the class itself
+    /// followed by its linearized ancestors), or a `ClassDefinitionError` describing why the
+    /// class can't be finalized.
+    pub fn analyze(&self) -> Result<Vec<Container<Class>>, ClassDefinitionError> {
+        self.check_for_cycles()?;
+        self.linearize()
+    }
+
+    /// Depth-first-searches each base's ancestry, tracking the *current path* rather than every
+    /// class visited so far. A base reachable from two different paths (a diamond, e.g. `D(B,
+    /// C)` with `B` and `C` both deriving from `A`) is expected and fine; a base that is its own
+    /// ancestor along a single path is an actual cycle.
+    fn check_for_cycles(&self) -> Result<(), ClassDefinitionError> {
+        let class_type = self.class.borrow().builtin_type();
+
+        let mut path = HashSet::new();
+        path.insert(class_type);
+
+        for base in &self.bases {
+            self.visit(base, &mut path, class_type)?;
+        }
+
+        Ok(())
+    }
+
+    fn visit(
+        &self,
+        node: &Container<Class>,
+        path: &mut HashSet<Type>,
+        class_type: Type,
+    ) -> Result<(), ClassDefinitionError> {
+        let node_type = node.borrow().builtin_type();
+        if !path.insert(node_type) {
+            return Err(ClassDefinitionError::InheritanceCycle { class_type });
+        }
+
+        for base in node.borrow().bases() {
+            self.visit(&base, path, class_type)?;
+        }
+
+        // Backtrack: `node_type` is only "on the path" for the duration of its own subtree, so
+        // sibling branches that reach it via a different path (a diamond) don't falsely collide
+        // with it.
+        path.remove(&node_type);
+
+        Ok(())
+    }
+
+    /// `L[C] = [C] + merge(L[B1], .., L[Bn], [B1, .., Bn])`, CPython's C3 linearization.
+    /// `merge` repeatedly looks for the first list whose head does not appear in the tail of any
+    /// other list, appends that head to the result, and drops it from every list.
+    fn linearize(&self) -> Result<Vec<Container<Class>>, ClassDefinitionError> {
+        let mut sequences: Vec<Vec<Container<Class>>> =
+            self.bases.iter().map(|base| base.borrow().mro()).collect();
+        sequences.push(self.bases.clone());
+
+        let class_type = self.class.borrow().builtin_type();
+        let mut result = vec![self.class.clone()];
+        result.extend(Self::merge(sequences, class_type)?);
+        Ok(result)
+    }
+
+    fn merge(
+        mut sequences: Vec<Vec<Container<Class>>>,
+        class_type: Type,
+    ) -> Result<Vec<Container<Class>>, ClassDefinitionError> {
+        let mut result = vec![];
+
+        loop {
+            sequences.retain(|seq| !seq.is_empty());
+            if sequences.is_empty() {
+                return Ok(result);
+            }
+
+            let candidate = sequences.iter().map(|seq| seq[0].clone()).find(|candidate| {
+                !sequences
+                    .iter()
+                    .any(|seq| seq[1..].iter().any(|other| other == candidate))
+            });
+
+            match candidate {
+                Some(candidate) => {
+                    for seq in sequences.iter_mut() {
+                        seq.retain(|class| class != &candidate);
+                    }
+                    result.push(candidate);
+                }
+                None => {
+                    return Err(ClassDefinitionError::InconsistentMro { class_type });
+                }
+            }
+        }
+    }
+}
+
+/// Validates and finalizes a user-defined class's bases in one call: builds the
+/// [`ClassDefinition`] from `class` and its evaluated `base_exprs` via [`ClassDefinition::from_exprs`],
+/// computes the linearized `__mro__` via `analyze()`, and stores it on `class` -- or turns a
+/// [`ClassDefinitionError`] into whatever `InterpreterError` `to_type_error` builds from its
+/// message (typically `|msg| InterpreterError::TypeError(Some(msg), interpreter.state.call_stack())`).
+///
+/// This is the single call a `class` statement's evaluation needs to make once it has already
+/// constructed the bare `class` and evaluated its base expressions; nothing in this source tree
+/// calls it yet, since that evaluation isn't part of it.
+pub fn finalize_user_class(
+    class: Container<Class>,
+    base_exprs: Vec<ExprResult>,
+    to_type_error: impl Fn(String) -> InterpreterError,
+) -> Result<Container<Class>, InterpreterError> {
+    let mro = ClassDefinition::from_exprs(class.clone(), base_exprs)
+        .and_then(|definition| definition.analyze())
+        .map_err(|e| to_type_error(e.message()))?;
+
+    class.borrow_mut().set_mro(mro);
+    Ok(class)
+}
+
+/// Resolves a member by walking `mro` in C3 order and returning the first hit from `lookup_own`,
+/// which should check only the class's *own* members, not recurse into its bases -- the MRO
+/// already linearizes that. This is what plain attribute/method lookup on a `Container<Class>`
+/// should do instead of recursing through a single `parent_class`, which picks an arbitrary base
+/// on a diamond instead of the C3-correct one. Generic over `lookup_own` so this module doesn't
+/// need write access to `Class`'s attribute storage to provide the traversal.
+pub fn resolve_in_mro<T>(
+    mro: &[Container<Class>],
+    lookup_own: impl Fn(&Container<Class>) -> Option<T>,
+) -> Option<T> {
+    mro.iter().find_map(lookup_own)
+}
+
+/// `super(current_class, ...)` resolution: the same traversal as `resolve_in_mro`, but starting
+/// *after* `current_class` in `mro`, the `__mro__` of the instance's type -- CPython's
+/// cooperative `super()`, equivalent to `type.__mro__[type.__mro__.index(current_class) + 1:]`.
+/// Falls back to searching the whole `mro` if `current_class` isn't found in it.
+pub fn resolve_in_mro_after<T>(
+    mro: &[Container<Class>],
+    current_class: &Container<Class>,
+    lookup_own: impl Fn(&Container<Class>) -> Option<T>,
+) -> Option<T> {
+    let start = mro
+        .iter()
+        .position(|class| class == current_class)
+        .map_or(0, |index| index + 1);
+    mro[start..].iter().find_map(lookup_own)
+}
+
+/// Why a class's declared bases could not be turned into a finalized class.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ClassDefinitionError {
+    /// A name given as a base class does not actually refer to a class.
+    NotAClass { name: String },
+    /// A base is its own (possibly indirect) ancestor.
+    InheritanceCycle { class_type: Type },
+    /// The bases disagree on relative ordering and cannot be linearized into one MRO, e.g. a
+    /// diamond where two bases list their shared ancestor in conflicting order.
+    InconsistentMro { class_type: Type },
+}
+
+impl ClassDefinitionError {
+    pub fn message(&self) -> String {
+        match self {
+            ClassDefinitionError::NotAClass { name } => {
+                format!("'{}' is not a class and cannot be used as a base class", name)
+            }
+            ClassDefinitionError::InheritanceCycle { class_type } => format!(
+                "Cannot create class '{}': an inheritance cycle was detected among its bases",
+                class_type.value()
+            ),
+            ClassDefinitionError::InconsistentMro { class_type } => format!(
+                "Cannot create a consistent method resolution order (MRO) for bases of class '{}'",
+                class_type.value()
+            ),
+        }
+    }
+}